@@ -0,0 +1,191 @@
+use lopdf::{dictionary, Document, Object, Stream};
+
+/// PDF conformance level set with `roc_fx_setConformance`, mirroring printpdf's
+/// `PdfConformance`. Only the level this crate actually knows how to produce is
+/// modeled; anything beyond "no particular conformance" requires extra machinery
+/// (embedded ICC profile, XMP conformance flags, embedded-fonts enforcement) wired
+/// up in [`crate::document::PdfDocument::build`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Conformance {
+    #[default]
+    None,
+    PdfA2b,
+}
+
+impl Conformance {
+    pub fn parse(level: &str) -> Result<Self, String> {
+        match level.trim().to_ascii_lowercase().as_str() {
+            "" | "none" => Ok(Conformance::None),
+            "pdfa-2b" | "pdf/a-2b" | "pdfa2b" => Ok(Conformance::PdfA2b),
+            other => Err(format!("unknown conformance level: {other}")),
+        }
+    }
+
+    /// The minimum PDF version a conformant file must declare.
+    pub fn required_pdf_version(self) -> &'static str {
+        match self {
+            Conformance::None => "1.5",
+            Conformance::PdfA2b => "1.7",
+        }
+    }
+
+    /// The `pdfaid:part` / `pdfaid:conformance` pair to add to the XMP packet.
+    pub fn xmp_pdfaid(self) -> Option<(&'static str, &'static str)> {
+        match self {
+            Conformance::None => None,
+            Conformance::PdfA2b => Some(("2", "B")),
+        }
+    }
+}
+
+/// Adds an `/OutputIntents` array to `catalog` with a single sRGB `GTS_PDFA1` intent,
+/// embedding the ICC profile as a stream, and returns the updated catalog.
+pub fn add_output_intent(doc: &mut Document, mut catalog: lopdf::Dictionary) -> lopdf::Dictionary {
+    let icc_bytes = srgb_icc_profile();
+    let icc_id = doc.add_object(Stream::new(
+        dictionary! { "N" => 3 },
+        icc_bytes,
+    ));
+
+    let intent_id = doc.add_object(dictionary! {
+        "Type" => "OutputIntent",
+        "S" => "GTS_PDFA1",
+        "OutputConditionIdentifier" => Object::string_literal("sRGB"),
+        "DestOutputProfile" => icc_id,
+        "N" => 3,
+    });
+
+    catalog.set("OutputIntents", vec![intent_id.into()]);
+    catalog
+}
+
+/// Checks the constraints PDF/A-2b requires: every font must be embedded (the 14
+/// standard Type1 fonts aren't, since they rely on the viewer providing them).
+pub fn check_constraints(fonts: &[crate::document::FontEntry]) -> Result<(), String> {
+    for font in fonts {
+        if let crate::document::FontEntry::Builtin { base_font } = font {
+            return Err(format!(
+                "PDF/A-2b requires all fonts to be embedded, but \"{base_font}\" is a \
+                 standard (non-embedded) font -- use roc_fx_loadFont instead"
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+fn s15_fixed16(value: f64) -> [u8; 4] {
+    ((value * 65536.0).round() as i32).to_be_bytes()
+}
+
+/// A minimal, self-contained ICC v2 display profile for sRGB, just complete enough
+/// to satisfy PDF/A's `/OutputIntent` requirement: header, white point, RGB
+/// colorants and linear tone curves. Real-world PDF/A producers ship the full
+/// ~3KB sRGB ICC profile; we synthesize a reduced one rather than vendoring a
+/// binary asset.
+fn srgb_icc_profile() -> Vec<u8> {
+    // Tag data, built first so we know their offsets.
+    let desc_tag = text_description_tag("sRGB");
+    let copyright_tag = text_description_tag("Public Domain");
+    let white_point_tag = xyz_tag(0.9642, 1.0, 0.8249); // D50
+    let red_tag = xyz_tag(0.4360, 0.2225, 0.0139);
+    let green_tag = xyz_tag(0.3851, 0.7169, 0.0971);
+    let blue_tag = xyz_tag(0.1431, 0.0606, 0.7139);
+    let curve_tag = linear_curve_tag();
+
+    let tags: Vec<(&[u8; 4], Vec<u8>)> = vec![
+        (b"desc", desc_tag),
+        (b"cprt", copyright_tag),
+        (b"wtpt", white_point_tag),
+        (b"rXYZ", red_tag.clone()),
+        (b"gXYZ", green_tag.clone()),
+        (b"bXYZ", blue_tag.clone()),
+        (b"rTRC", curve_tag.clone()),
+        (b"gTRC", curve_tag.clone()),
+        (b"bTRC", curve_tag),
+    ];
+
+    let header_size = 128;
+    let tag_table_size = 4 + tags.len() * 12;
+    let mut offset = header_size + tag_table_size;
+
+    let mut tag_table = Vec::new();
+    tag_table.extend_from_slice(&(tags.len() as u32).to_be_bytes());
+
+    let mut tag_data = Vec::new();
+    for (sig, data) in &tags {
+        tag_table.extend_from_slice(*sig);
+        tag_table.extend_from_slice(&(offset as u32).to_be_bytes());
+        tag_table.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        tag_data.extend_from_slice(data);
+        offset += data.len();
+    }
+
+    let total_size = header_size + tag_table.len() + tag_data.len();
+
+    let mut profile = Vec::with_capacity(total_size);
+    profile.extend_from_slice(&(total_size as u32).to_be_bytes()); // profile size
+    profile.extend_from_slice(&[0; 4]); // CMM type
+    profile.extend_from_slice(&[0x02, 0x10, 0x00, 0x00]); // version 2.1.0
+    profile.extend_from_slice(b"mntr"); // device class: monitor
+    profile.extend_from_slice(b"RGB "); // colour space
+    profile.extend_from_slice(b"XYZ "); // PCS
+    profile.extend_from_slice(&[0; 12]); // date/time created, left zeroed
+    profile.extend_from_slice(b"acsp"); // profile file signature
+    profile.extend_from_slice(&[0; 4]); // primary platform
+    profile.extend_from_slice(&[0; 4]); // profile flags
+    profile.extend_from_slice(&[0; 4]); // device manufacturer
+    profile.extend_from_slice(&[0; 4]); // device model
+    profile.extend_from_slice(&[0; 8]); // device attributes
+    profile.extend_from_slice(&[0; 4]); // rendering intent: perceptual
+    profile.extend_from_slice(&s15_fixed16(0.9642)); // PCS illuminant X (D50)
+    profile.extend_from_slice(&s15_fixed16(1.0)); // PCS illuminant Y
+    profile.extend_from_slice(&s15_fixed16(0.8249)); // PCS illuminant Z
+    profile.extend_from_slice(&[0; 4]); // profile creator
+    profile.extend_from_slice(&[0; 44]); // reserved
+
+    profile.extend_from_slice(&tag_table);
+    profile.extend_from_slice(&tag_data);
+
+    profile
+}
+
+/// A `textDescriptionType` tag (ICC v2) holding only the ASCII invariant description,
+/// with empty Unicode and ScriptCode portions.
+fn text_description_tag(ascii: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(b"desc");
+    data.extend_from_slice(&[0; 4]); // reserved
+
+    let ascii_with_nul = [ascii.as_bytes(), &[0]].concat();
+    data.extend_from_slice(&(ascii_with_nul.len() as u32).to_be_bytes());
+    data.extend_from_slice(&ascii_with_nul);
+
+    data.extend_from_slice(&[0; 4]); // Unicode language code
+    data.extend_from_slice(&[0; 4]); // Unicode description length (none)
+    data.extend_from_slice(&[0; 2]); // ScriptCode code
+    data.extend_from_slice(&[0; 1]); // ScriptCode description length
+    data.extend_from_slice(&[0; 67]); // ScriptCode description buffer
+
+    data
+}
+
+/// An `XYZType` tag holding a single CIE XYZ triple.
+fn xyz_tag(x: f64, y: f64, z: f64) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(b"XYZ ");
+    data.extend_from_slice(&[0; 4]); // reserved
+    data.extend_from_slice(&s15_fixed16(x));
+    data.extend_from_slice(&s15_fixed16(y));
+    data.extend_from_slice(&s15_fixed16(z));
+    data
+}
+
+/// A `curveType` tag with zero entries, meaning an identity (linear) tone curve.
+fn linear_curve_tag() -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(b"curv");
+    data.extend_from_slice(&[0; 4]); // reserved
+    data.extend_from_slice(&0u32.to_be_bytes()); // count = 0 -> identity curve
+    data
+}
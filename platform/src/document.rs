@@ -0,0 +1,500 @@
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Document, Object};
+
+use crate::conformance::{self, Conformance};
+use crate::font::EmbeddedFont;
+use crate::image::{EmbeddedImage, ImageFormat};
+use crate::metadata::DocumentMetadata;
+use crate::outline::{self, BookmarkEntry, BookmarkId};
+
+/// One page accumulated by the Roc program via `roc_fx_addPage` / `roc_fx_addText`.
+///
+/// Object ids for the page, its content stream, and the shared page tree are only
+/// allocated once, in [`PdfDocument::build`] -- until then a page is just data.
+#[derive(Debug)]
+pub struct PageData {
+    pub width: f64,
+    pub height: f64,
+    pub operations: Vec<Operation>,
+    /// Current `RG` stroke color, set by `roc_fx_setStrokeColor` and applied by the
+    /// next `roc_fx_drawLine` / `roc_fx_drawRect`.
+    stroke_color: (f64, f64, f64),
+    /// Current `rg` fill color, set by `roc_fx_setFillColor` and applied by the next
+    /// `roc_fx_fillRect`.
+    fill_color: (f64, f64, f64),
+    /// Current `w` line width, set by `roc_fx_setLineWidth` and applied by the next
+    /// `roc_fx_drawLine` / `roc_fx_drawRect`.
+    line_width: f64,
+}
+
+impl PageData {
+    fn new(width: f64, height: f64) -> Self {
+        PageData {
+            width,
+            height,
+            operations: Vec::new(),
+            stroke_color: (0.0, 0.0, 0.0),
+            fill_color: (0.0, 0.0, 0.0),
+            line_width: 1.0,
+        }
+    }
+}
+
+/// A font registered with [`PdfDocument::set_font`] or [`PdfDocument::load_font`].
+#[derive(Debug)]
+pub enum FontEntry {
+    /// One of the 14 standard Type1 fonts, e.g. `"Courier"` or `"Helvetica"`.
+    Builtin { base_font: String },
+    /// A TrueType/OpenType font embedded as a Type0/CIDFontType2 composite font.
+    External(EmbeddedFont),
+}
+
+/// An in-progress PDF document as seen by the Roc effects in `lib.rs`.
+///
+/// Pages and fonts are appended to as Roc calls `roc_fx_addPage` / `roc_fx_addText` /
+/// `roc_fx_setFont`; the `lopdf::Document` itself, and all of its object ids, are only
+/// built once at save time by [`PdfDocument::build`].
+#[derive(Debug)]
+pub struct PdfDocument {
+    doc: Document,
+    pages: Vec<PageData>,
+    fonts: Vec<FontEntry>,
+    images: Vec<EmbeddedImage>,
+    metadata: DocumentMetadata,
+    bookmarks: Vec<BookmarkEntry>,
+    conformance: Conformance,
+}
+
+/// Index of a page accumulated on a [`PdfDocument`]. Returned to Roc as a plain `U64`.
+pub type PageId = usize;
+
+/// Index of a font registered on a [`PdfDocument`]. Returned to Roc as a plain `U64`.
+pub type FontRef = usize;
+
+impl PdfDocument {
+    pub fn new() -> Self {
+        PdfDocument {
+            doc: Document::with_version("1.5"),
+            pages: Vec::new(),
+            fonts: Vec::new(),
+            images: Vec::new(),
+            metadata: DocumentMetadata::default(),
+            bookmarks: Vec::new(),
+            conformance: Conformance::None,
+        }
+    }
+
+    pub fn set_metadata(&mut self, metadata: DocumentMetadata) {
+        self.metadata = metadata;
+    }
+
+    pub fn set_conformance(&mut self, conformance: Conformance) {
+        self.conformance = conformance;
+    }
+
+    /// Adds an outline (bookmark) entry pointing at the top of `page_id`, optionally
+    /// nested under an existing bookmark, and returns its `BookmarkId`.
+    pub fn add_bookmark(
+        &mut self,
+        title: String,
+        page_id: PageId,
+        parent: Option<BookmarkId>,
+    ) -> Result<BookmarkId, String> {
+        if page_id >= self.pages.len() {
+            return Err(format!("no such page: {page_id}"));
+        }
+        if let Some(parent) = parent {
+            if parent >= self.bookmarks.len() {
+                return Err(format!("no such bookmark: {parent}"));
+            }
+        }
+
+        self.bookmarks.push(BookmarkEntry {
+            title,
+            page_id,
+            parent,
+        });
+
+        Ok(self.bookmarks.len() - 1)
+    }
+
+    pub fn add_page(&mut self, width: f64, height: f64) -> PageId {
+        self.pages.push(PageData::new(width, height));
+        self.pages.len() - 1
+    }
+
+    pub fn set_font(&mut self, base_font: String) -> FontRef {
+        self.fonts.push(FontEntry::Builtin { base_font });
+        self.fonts.len() - 1
+    }
+
+    /// Parses a `.ttf`/`.otf` font program and registers it for use with
+    /// `roc_fx_addText`, returning a `FontRef` just like `set_font`.
+    pub fn load_font(&mut self, bytes: Vec<u8>) -> Result<FontRef, String> {
+        let font = EmbeddedFont::parse(bytes)?;
+        self.fonts.push(FontEntry::External(font));
+        Ok(self.fonts.len() - 1)
+    }
+
+    pub fn add_text(
+        &mut self,
+        page_id: PageId,
+        font_ref: FontRef,
+        size: f64,
+        x: f64,
+        y: f64,
+        text: &str,
+    ) -> Result<(), String> {
+        let font_name = self.font_resource_name(font_ref)?;
+
+        let text_string = match self
+            .fonts
+            .get_mut(font_ref)
+            .ok_or_else(|| format!("no such font: {font_ref}"))?
+        {
+            FontEntry::Builtin { .. } => Object::string_literal(text),
+            FontEntry::External(font) => {
+                Object::String(font.encode(text), lopdf::StringFormat::Hexadecimal)
+            }
+        };
+
+        let page = self
+            .pages
+            .get_mut(page_id)
+            .ok_or_else(|| format!("no such page: {page_id}"))?;
+
+        page.operations.push(Operation::new("BT", vec![]));
+        page.operations
+            .push(Operation::new("Tf", vec![font_name.into(), size.into()]));
+        page.operations
+            .push(Operation::new("Td", vec![x.into(), y.into()]));
+        page.operations
+            .push(Operation::new("Tj", vec![text_string]));
+        page.operations.push(Operation::new("ET", vec![]));
+
+        Ok(())
+    }
+
+    /// Decodes `bytes` as a JPEG or PNG (per `format`) and places it on `page_id` as
+    /// an `/Image` XObject, scaled to `width` x `height` PDF points with its
+    /// lower-left corner at `(x, y)`. Unlike fonts and bookmarks, an image isn't
+    /// registered for reuse -- each call decodes and embeds its own XObject, named
+    /// after its position in `images` the same way fonts are named after their
+    /// position in `fonts`.
+    pub fn add_image(
+        &mut self,
+        page_id: PageId,
+        bytes: Vec<u8>,
+        format: &str,
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    ) -> Result<(), String> {
+        if page_id >= self.pages.len() {
+            return Err(format!("no such page: {page_id}"));
+        }
+
+        let format = ImageFormat::parse(format)?;
+        let image = EmbeddedImage::parse(bytes, format)?;
+        self.images.push(image);
+        let image_name = format!("Img{}", self.images.len());
+
+        let page = &mut self.pages[page_id];
+        page.operations.push(Operation::new("q", vec![]));
+        page.operations.push(Operation::new(
+            "cm",
+            vec![
+                width.into(),
+                0.into(),
+                0.into(),
+                height.into(),
+                x.into(),
+                y.into(),
+            ],
+        ));
+        page.operations.push(Operation::new(
+            "Do",
+            vec![Object::Name(image_name.into_bytes())],
+        ));
+        page.operations.push(Operation::new("Q", vec![]));
+
+        Ok(())
+    }
+
+    /// Sets the `RG` stroke color used by later `roc_fx_drawLine` / `roc_fx_drawRect`
+    /// calls on `page_id`. Components are 0-1 RGB, matching the `rg`/`RG` operators.
+    pub fn set_stroke_color(
+        &mut self,
+        page_id: PageId,
+        r: f64,
+        g: f64,
+        b: f64,
+    ) -> Result<(), String> {
+        self.page_mut(page_id)?.stroke_color = (r, g, b);
+        Ok(())
+    }
+
+    /// Sets the `rg` fill color used by later `roc_fx_fillRect` calls on `page_id`.
+    pub fn set_fill_color(&mut self, page_id: PageId, r: f64, g: f64, b: f64) -> Result<(), String> {
+        self.page_mut(page_id)?.fill_color = (r, g, b);
+        Ok(())
+    }
+
+    /// Sets the `w` line width used by later `roc_fx_drawLine` / `roc_fx_drawRect`
+    /// calls on `page_id`, in PDF points.
+    pub fn set_line_width(&mut self, page_id: PageId, width: f64) -> Result<(), String> {
+        self.page_mut(page_id)?.line_width = width;
+        Ok(())
+    }
+
+    /// Draws a straight line from `(x1, y1)` to `(x2, y2)` using the page's current
+    /// stroke color and line width, wrapped in `q`/`Q` so neither leaks into later
+    /// text or drawing.
+    pub fn draw_line(
+        &mut self,
+        page_id: PageId,
+        x1: f64,
+        y1: f64,
+        x2: f64,
+        y2: f64,
+    ) -> Result<(), String> {
+        let page = self.page_mut(page_id)?;
+        let (r, g, b) = page.stroke_color;
+        let line_width = page.line_width;
+
+        page.operations.push(Operation::new("q", vec![]));
+        page.operations.push(Operation::new("w", vec![line_width.into()]));
+        page.operations
+            .push(Operation::new("RG", vec![r.into(), g.into(), b.into()]));
+        page.operations
+            .push(Operation::new("m", vec![x1.into(), y1.into()]));
+        page.operations
+            .push(Operation::new("l", vec![x2.into(), y2.into()]));
+        page.operations.push(Operation::new("S", vec![]));
+        page.operations.push(Operation::new("Q", vec![]));
+
+        Ok(())
+    }
+
+    /// Strokes the outline of a `width` x `height` rectangle with its lower-left
+    /// corner at `(x, y)`, using the page's current stroke color and line width,
+    /// wrapped in `q`/`Q` so neither leaks into later text or drawing.
+    pub fn draw_rect(
+        &mut self,
+        page_id: PageId,
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    ) -> Result<(), String> {
+        let page = self.page_mut(page_id)?;
+        let (r, g, b) = page.stroke_color;
+        let line_width = page.line_width;
+
+        page.operations.push(Operation::new("q", vec![]));
+        page.operations.push(Operation::new("w", vec![line_width.into()]));
+        page.operations
+            .push(Operation::new("RG", vec![r.into(), g.into(), b.into()]));
+        page.operations.push(Operation::new(
+            "re",
+            vec![x.into(), y.into(), width.into(), height.into()],
+        ));
+        page.operations.push(Operation::new("S", vec![]));
+        page.operations.push(Operation::new("Q", vec![]));
+
+        Ok(())
+    }
+
+    /// Fills a `width` x `height` rectangle with its lower-left corner at `(x, y)`,
+    /// using the page's current fill color, wrapped in `q`/`Q` so it doesn't leak
+    /// into later text or drawing.
+    pub fn fill_rect(
+        &mut self,
+        page_id: PageId,
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    ) -> Result<(), String> {
+        let page = self.page_mut(page_id)?;
+        let (r, g, b) = page.fill_color;
+
+        page.operations.push(Operation::new("q", vec![]));
+        page.operations
+            .push(Operation::new("rg", vec![r.into(), g.into(), b.into()]));
+        page.operations.push(Operation::new(
+            "re",
+            vec![x.into(), y.into(), width.into(), height.into()],
+        ));
+        page.operations.push(Operation::new("f", vec![]));
+        page.operations.push(Operation::new("Q", vec![]));
+
+        Ok(())
+    }
+
+    fn page_mut(&mut self, page_id: PageId) -> Result<&mut PageData, String> {
+        self.pages
+            .get_mut(page_id)
+            .ok_or_else(|| format!("no such page: {page_id}"))
+    }
+
+    /// The `/F1`, `/F2`, ... name a font is registered under in the shared `Resources`
+    /// dictionary. Names are 1-indexed: `FontRef` 0 is `/F1`.
+    fn font_resource_name(&self, font_ref: FontRef) -> Result<String, String> {
+        if font_ref >= self.fonts.len() {
+            return Err(format!("no such font: {font_ref}"));
+        }
+
+        Ok(format!("F{}", font_ref + 1))
+    }
+
+    /// Rebuilds the underlying `lopdf::Document` from the accumulated pages and fonts:
+    /// one object each for every page's content stream and page dictionary, the shared
+    /// font `Resources`, the `Pages` tree root, and the document catalog.
+    ///
+    /// Called by `roc_fx_saveDocument` right before each save writes the document out,
+    /// so that Roc can keep calling `roc_fx_addPage` / `roc_fx_addText` in any order up
+    /// until save time.
+    pub fn build(&mut self) -> Result<(), String> {
+        if self.pages.is_empty() {
+            return Err("a PDF must have at least one page".into());
+        }
+
+        if self.conformance != Conformance::None {
+            conformance::check_constraints(&self.fonts)?;
+        }
+
+        self.doc = Document::with_version(self.conformance.required_pdf_version());
+
+        let mut font_dict = Vec::with_capacity(self.fonts.len());
+        for (i, font) in self.fonts.iter().enumerate() {
+            let font_id = match font {
+                FontEntry::Builtin { base_font } => self.doc.add_object(dictionary! {
+                    "Type" => "Font",
+                    "Subtype" => "Type1",
+                    "BaseFont" => base_font.as_str(),
+                }),
+                FontEntry::External(font) => font.build_font_dict(&mut self.doc),
+            };
+
+            font_dict.push((format!("F{}", i + 1), font_id.into()));
+        }
+
+        let mut xobject_dict = Vec::with_capacity(self.images.len());
+        for (i, image) in self.images.iter().enumerate() {
+            let xobject_id = image.build_xobject(&mut self.doc);
+            xobject_dict.push((format!("Img{}", i + 1), xobject_id.into()));
+        }
+
+        let mut resources =
+            dictionary! { "Font" => Object::Dictionary(lopdf::Dictionary::from_iter(font_dict)) };
+        if !xobject_dict.is_empty() {
+            resources.set(
+                "XObject",
+                Object::Dictionary(lopdf::Dictionary::from_iter(xobject_dict)),
+            );
+        }
+        let resources_id = self.doc.add_object(resources);
+
+        let pages_id = self.doc.new_object_id();
+
+        let page_ids: Vec<lopdf::ObjectId> = self
+            .pages
+            .iter()
+            .map(|page| {
+                let content = Content {
+                    operations: page.operations.clone(),
+                };
+                let content_id = self
+                    .doc
+                    .add_object(lopdf::Stream::new(dictionary! {}, content.encode().unwrap()));
+
+                self.doc.add_object(dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages_id,
+                    "Contents" => content_id,
+                    "MediaBox" => vec![0.into(), 0.into(), page.width.into(), page.height.into()],
+                })
+            })
+            .collect();
+
+        let page_count = page_ids.len() as i64;
+        let kids: Vec<Object> = page_ids.iter().map(|&id| id.into()).collect();
+
+        let pages = dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => page_count,
+            "Resources" => resources_id,
+        };
+        self.doc.objects.insert(pages_id, Object::Dictionary(pages));
+
+        let pages_with_heights: Vec<(lopdf::ObjectId, f64)> = page_ids
+            .iter()
+            .zip(self.pages.iter())
+            .map(|(&id, page)| (id, page.height))
+            .collect();
+        let outline_id = outline::build_outline(&mut self.doc, &self.bookmarks, &pages_with_heights);
+
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or(0);
+
+        let metadata_id = self
+            .doc
+            .add_object(self.metadata.build_xmp_stream(now, self.conformance.xmp_pdfaid()));
+
+        let mut catalog = dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+            "Metadata" => metadata_id,
+        };
+        if let Some(outline_id) = outline_id {
+            catalog.set("Outlines", outline_id);
+        }
+        if self.conformance != Conformance::None {
+            catalog = conformance::add_output_intent(&mut self.doc, catalog);
+        }
+        let catalog_id = self.doc.add_object(catalog);
+
+        self.metadata.write_info_dict(&mut self.doc, now);
+        self.doc.trailer.set("Root", catalog_id);
+
+        if self.conformance != Conformance::None {
+            let id = document_id(&self.metadata.title, now);
+            self.doc
+                .trailer
+                .set("ID", vec![id.clone().into(), id.into()]);
+        }
+
+        Ok(())
+    }
+
+    pub fn doc_mut(&mut self) -> &mut Document {
+        &mut self.doc
+    }
+}
+
+/// A 16-byte trailer `/ID` value derived from the document's title and save time.
+/// Not cryptographically random, but unique enough across documents for PDF/A's
+/// requirement that `/ID` be present.
+fn document_id(title: &str, now: i64) -> Object {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = DefaultHasher::new();
+    title.hash(&mut hasher);
+    now.hash(&mut hasher);
+    let first_half = hasher.finish();
+
+    title.hash(&mut hasher);
+    let second_half = hasher.finish();
+
+    let mut bytes = Vec::with_capacity(16);
+    bytes.extend_from_slice(&first_half.to_be_bytes());
+    bytes.extend_from_slice(&second_half.to_be_bytes());
+
+    Object::String(bytes, lopdf::StringFormat::Hexadecimal)
+}
@@ -0,0 +1,232 @@
+use std::collections::{BTreeSet, HashMap};
+
+use lopdf::{dictionary, Document, Object, ObjectId, Stream};
+use ttf_parser::Face;
+
+use crate::subset;
+
+/// A TrueType/OpenType font loaded with `roc_fx_loadFont`, embedded as a composite
+/// (Type0/CIDFontType2) font so that non-Latin1 text and full Unicode extraction work.
+///
+/// Unlike the 14 standard fonts registered with `roc_fx_setFont`, the font *program*
+/// itself (`bytes`) is embedded in the PDF via `/FontFile2`. `build_font_dict` subsets
+/// it down to `used_glyphs` (via [`subset::subset_truetype`]) before embedding, and the
+/// `/W` widths array and `/ToUnicode` CMap are only ever built for the glyphs a document
+/// actually uses, tracked here in `used_glyphs` as `roc_fx_addText` calls come in.
+#[derive(Debug)]
+pub struct EmbeddedFont {
+    bytes: Vec<u8>,
+    base_font: String,
+    units_per_em: u16,
+    ascent: i16,
+    descent: i16,
+    cap_height: i16,
+    italic_angle: f32,
+    bbox: [i16; 4],
+    flags: u32,
+    /// Advance widths in font units, keyed by glyph id.
+    glyph_widths: HashMap<u16, u16>,
+    /// Unicode scalar value -> glyph id, from the font's cmap table.
+    cmap: HashMap<char, u16>,
+    used_glyphs: BTreeSet<u16>,
+}
+
+impl EmbeddedFont {
+    pub fn parse(bytes: Vec<u8>) -> Result<Self, String> {
+        let face = Face::parse(&bytes, 0).map_err(|err| format!("invalid font file: {err}"))?;
+
+        let units_per_em = face.units_per_em();
+
+        let mut cmap = HashMap::new();
+        if let Some(cmap_table) = face.tables().cmap {
+            for subtable in cmap_table.subtables {
+                if !subtable.is_unicode() {
+                    continue;
+                }
+                subtable.codepoints(|codepoint| {
+                    if let Some(ch) = char::from_u32(codepoint) {
+                        if let Some(glyph_id) = subtable.glyph_index(codepoint) {
+                            cmap.entry(ch).or_insert(glyph_id.0);
+                        }
+                    }
+                });
+            }
+        }
+
+        let mut glyph_widths = HashMap::new();
+        for glyph_id in cmap.values() {
+            if let Some(width) = face.glyph_hor_advance(ttf_parser::GlyphId(*glyph_id)) {
+                glyph_widths.insert(*glyph_id, width);
+            }
+        }
+
+        let base_font = face
+            .names()
+            .into_iter()
+            .find(|name| name.name_id == ttf_parser::name_id::POST_SCRIPT_NAME)
+            .and_then(|name| name.to_string())
+            .unwrap_or_else(|| "EmbeddedFont".to_string());
+
+        let bbox = face.global_bounding_box();
+
+        Ok(EmbeddedFont {
+            bytes,
+            base_font,
+            units_per_em,
+            ascent: face.ascender(),
+            descent: face.descender(),
+            cap_height: face.capital_height().unwrap_or(face.ascender()),
+            italic_angle: if face.is_italic() { -12.0 } else { 0.0 },
+            bbox: [bbox.x_min, bbox.y_min, bbox.x_max, bbox.y_max],
+            // bit 6 (0x20) = nonsymbolic, bit 7 (0x40) = italic
+            flags: if face.is_italic() { 0x20 | 0x40 } else { 0x20 },
+            glyph_widths,
+            cmap,
+            used_glyphs: BTreeSet::new(),
+        })
+    }
+
+    /// Scales a font-unit value (advance widths, bbox, ...) to the `/1000 em` space
+    /// every PDF font metric is expressed in.
+    fn scale_to_1000(&self, value: i32) -> i64 {
+        (value as f64 * 1000.0 / self.units_per_em as f64).round() as i64
+    }
+
+    /// Encodes `text` as the 2-byte-per-glyph string a `/Encoding /Identity-H` composite
+    /// font expects, recording every glyph used so `build_font_dict` can emit widths and
+    /// ToUnicode entries for exactly the glyphs this document needs.
+    pub fn encode(&mut self, text: &str) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(text.len() * 2);
+
+        for ch in text.chars() {
+            let glyph_id = self.cmap.get(&ch).copied().unwrap_or(0);
+            self.used_glyphs.insert(glyph_id);
+            bytes.extend_from_slice(&glyph_id.to_be_bytes());
+        }
+
+        bytes
+    }
+
+    /// Builds the `/Type0` font dictionary (plus its `/CIDFontType2` descendant,
+    /// `/FontDescriptor`, `/FontFile2` and `/ToUnicode` stream) and adds them all to
+    /// `doc`, returning the id of the `/Type0` dictionary to reference from Resources.
+    pub fn build_font_dict(&self, doc: &mut Document) -> ObjectId {
+        let (subset_bytes, old_to_new_gid) = subset::subset_truetype(&self.bytes, &self.used_glyphs);
+
+        let font_file_id = doc.add_object(Stream::new(
+            dictionary! { "Length1" => subset_bytes.len() as i64 },
+            subset_bytes,
+        ));
+
+        let descriptor_id = doc.add_object(dictionary! {
+            "Type" => "FontDescriptor",
+            "FontName" => self.base_font.as_str(),
+            "Flags" => self.flags as i64,
+            "FontBBox" => vec![
+                self.scale_to_1000(self.bbox[0] as i32).into(),
+                self.scale_to_1000(self.bbox[1] as i32).into(),
+                self.scale_to_1000(self.bbox[2] as i32).into(),
+                self.scale_to_1000(self.bbox[3] as i32).into(),
+            ],
+            "ItalicAngle" => self.italic_angle as f64,
+            "Ascent" => self.scale_to_1000(self.ascent as i32),
+            "Descent" => self.scale_to_1000(self.descent as i32),
+            "CapHeight" => self.scale_to_1000(self.cap_height as i32),
+            "StemV" => 80,
+            "FontFile2" => font_file_id,
+        });
+
+        let w_array: Vec<Object> = {
+            let mut entries = Vec::new();
+            for &glyph_id in &self.used_glyphs {
+                let width = self
+                    .glyph_widths
+                    .get(&glyph_id)
+                    .copied()
+                    .unwrap_or(self.units_per_em / 2);
+                entries.push(Object::Integer(glyph_id as i64));
+                entries.push(Object::Array(vec![self.scale_to_1000(width as i32).into()]));
+            }
+            entries
+        };
+
+        // The content stream already has CIDs baked in as the *original* glyph ids
+        // (from `encode`, called before subsetting is possible), but the subsetted
+        // `/FontFile2` renumbers glyphs into a compact space -- so rather than an
+        // `/Identity` mapping, an explicit `/CIDToGIDMap` stream translates each CID
+        // to its new glyph id.
+        let max_cid = self.used_glyphs.iter().copied().max().unwrap_or(0) as usize;
+        let mut cid_to_gid_map = vec![0u8; (max_cid + 1) * 2];
+        for &cid in &self.used_glyphs {
+            let new_gid = old_to_new_gid.get(&cid).copied().unwrap_or(0);
+            let offset = cid as usize * 2;
+            cid_to_gid_map[offset..offset + 2].copy_from_slice(&new_gid.to_be_bytes());
+        }
+        let cid_to_gid_map_id = doc.add_object(Stream::new(dictionary! {}, cid_to_gid_map));
+
+        let descendant_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "CIDFontType2",
+            "BaseFont" => self.base_font.as_str(),
+            "CIDSystemInfo" => dictionary! {
+                "Registry" => Object::string_literal("Adobe"),
+                "Ordering" => Object::string_literal("Identity"),
+                "Supplement" => 0,
+            },
+            "FontDescriptor" => descriptor_id,
+            "DW" => self.scale_to_1000((self.units_per_em / 2) as i32),
+            "W" => w_array,
+            "CIDToGIDMap" => cid_to_gid_map_id,
+        });
+
+        let to_unicode_id = doc.add_object(self.build_to_unicode_cmap());
+
+        doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type0",
+            "BaseFont" => self.base_font.as_str(),
+            "Encoding" => "Identity-H",
+            "DescendantFonts" => vec![descendant_id.into()],
+            "ToUnicode" => to_unicode_id,
+        })
+    }
+
+    /// A minimal `/ToUnicode` CMap stream mapping each used glyph id back to the
+    /// Unicode scalar it was drawn for, so copy/paste and text extraction work.
+    fn build_to_unicode_cmap(&self) -> Stream {
+        let reverse: HashMap<u16, char> = self.cmap.iter().map(|(ch, gid)| (*gid, *ch)).collect();
+
+        let mut bfchar = String::new();
+        for &glyph_id in &self.used_glyphs {
+            let Some(ch) = reverse.get(&glyph_id) else {
+                continue;
+            };
+            bfchar.push_str(&format!(
+                "<{glyph_id:04X}> <{:04X}>\n",
+                *ch as u32
+            ));
+        }
+
+        let cmap = format!(
+            "/CIDInit /ProcSet findresource begin\n\
+             12 dict begin\n\
+             begincmap\n\
+             /CIDSystemInfo << /Registry (Adobe) /Ordering (UCS) /Supplement 0 >> def\n\
+             /CMapName /Adobe-Identity-UCS def\n\
+             /CMapType 2 def\n\
+             1 begincodespacerange\n\
+             <0000> <FFFF>\n\
+             endcodespacerange\n\
+             {count} beginbfchar\n\
+             {bfchar}\
+             endbfchar\n\
+             endcmap\n\
+             CMapName currentdict /CMap defineresource pop\n\
+             end\n\
+             end",
+            count = self.used_glyphs.len(),
+        );
+
+        Stream::new(dictionary! {}, cmap.into_bytes())
+    }
+}
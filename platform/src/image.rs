@@ -0,0 +1,214 @@
+use lopdf::{dictionary, Document, Object, ObjectId, Stream};
+
+/// Raster format accepted by `roc_fx_addImage`, picked explicitly by the caller
+/// rather than sniffed from the bytes so a truncated or mislabeled file fails fast
+/// with a clear error instead of guessing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+}
+
+impl ImageFormat {
+    pub fn parse(format: &str) -> Result<Self, String> {
+        match format.trim().to_ascii_lowercase().as_str() {
+            "jpeg" | "jpg" => Ok(ImageFormat::Jpeg),
+            "png" => Ok(ImageFormat::Png),
+            other => Err(format!("unsupported image format: {other}")),
+        }
+    }
+}
+
+/// Decoded form of an image passed to `roc_fx_addImage`: just enough to build an
+/// `/Image` XObject -- dimensions, color space, and either the original JPEG bytes
+/// (for `/DCTDecode`) or raw, unfiltered pixel samples left for
+/// [`crate::document::PdfDocument::build`]'s caller (`Document::compress`, at save
+/// time) to wrap in `/FlateDecode`, plus an optional alpha channel for `/SMask`.
+#[derive(Debug)]
+pub struct EmbeddedImage {
+    width: u32,
+    height: u32,
+    color_space: &'static str,
+    samples: ImageSamples,
+}
+
+#[derive(Debug)]
+enum ImageSamples {
+    /// Raw JPEG bytes, stored verbatim under `/Filter /DCTDecode`.
+    Jpeg(Vec<u8>),
+    /// Decoded 8-bit-per-channel samples, and a separate 8-bit alpha channel if the
+    /// source had one.
+    Raw {
+        color: Vec<u8>,
+        alpha: Option<Vec<u8>>,
+    },
+}
+
+impl EmbeddedImage {
+    pub fn parse(bytes: Vec<u8>, format: ImageFormat) -> Result<Self, String> {
+        match format {
+            ImageFormat::Jpeg => Self::parse_jpeg(bytes),
+            ImageFormat::Png => Self::parse_png(&bytes),
+        }
+    }
+
+    /// Scans JPEG markers for the first start-of-frame (`/DCTDecode` handles both
+    /// baseline and progressive scans the same way) to read `/Width`, `/Height` and
+    /// the component count -- the pixels themselves are left compressed and embedded
+    /// verbatim, since `/DCTDecode` lets the PDF viewer do the actual JPEG decoding.
+    fn parse_jpeg(bytes: Vec<u8>) -> Result<Self, String> {
+        if bytes.len() < 4 || bytes[0] != 0xFF || bytes[1] != 0xD8 {
+            return Err("not a JPEG file".into());
+        }
+
+        let mut i = 2;
+        while i + 4 <= bytes.len() {
+            if bytes[i] != 0xFF {
+                return Err("malformed JPEG marker".into());
+            }
+            let marker = bytes[i + 1];
+
+            // Markers with no payload: TEM and the RSTn/SOI/EOI standalone markers.
+            if marker == 0x01 || (0xD0..=0xD9).contains(&marker) {
+                i += 2;
+                continue;
+            }
+
+            let len = u16::from_be_bytes([bytes[i + 2], bytes[i + 3]]) as usize;
+            if i + 2 + len > bytes.len() {
+                return Err("truncated JPEG marker".into());
+            }
+
+            let is_sof = matches!(
+                marker,
+                0xC0 | 0xC1 | 0xC2 | 0xC3 | 0xC5 | 0xC6 | 0xC7 | 0xC9 | 0xCA | 0xCB | 0xCD | 0xCE
+                    | 0xCF
+            );
+            if is_sof {
+                if len < 8 {
+                    return Err("malformed JPEG SOF marker".into());
+                }
+                let sof = &bytes[i + 4..i + 2 + len];
+                let height = u16::from_be_bytes([sof[1], sof[2]]) as u32;
+                let width = u16::from_be_bytes([sof[3], sof[4]]) as u32;
+                let color_space = match sof[5] {
+                    1 => "DeviceGray",
+                    3 => "DeviceRGB",
+                    4 => "DeviceCMYK",
+                    other => return Err(format!("unsupported JPEG component count: {other}")),
+                };
+
+                return Ok(EmbeddedImage {
+                    width,
+                    height,
+                    color_space,
+                    samples: ImageSamples::Jpeg(bytes),
+                });
+            }
+
+            if marker == 0xDA {
+                break; // start of scan: no SOF marker can follow
+            }
+            i += 2 + len;
+        }
+
+        Err("no JPEG SOF marker found".into())
+    }
+
+    /// Fully decodes the PNG to raw samples -- unlike JPEG, `/FlateDecode` expects
+    /// unfiltered pixel data, so the PNG filters and any palette have to be undone
+    /// up front. An alpha channel, if present, is split out into its own buffer for
+    /// the `/SMask` sub-XObject.
+    fn parse_png(bytes: &[u8]) -> Result<Self, String> {
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+
+        let mut reader = decoder
+            .read_info()
+            .map_err(|err| format!("invalid PNG file: {err}"))?;
+
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader
+            .next_frame(&mut buf)
+            .map_err(|err| format!("invalid PNG file: {err}"))?;
+        buf.truncate(info.buffer_size());
+
+        let (color_space, channels, has_alpha) = match info.color_type {
+            png::ColorType::Grayscale => ("DeviceGray", 1, false),
+            png::ColorType::GrayscaleAlpha => ("DeviceGray", 2, true),
+            png::ColorType::Rgb => ("DeviceRGB", 3, false),
+            png::ColorType::Rgba => ("DeviceRGB", 4, true),
+            png::ColorType::Indexed => unreachable!("EXPAND transformation removes palettes"),
+        };
+
+        let (color, alpha) = if has_alpha {
+            let pixel_channels = channels - 1;
+            let pixel_count = (info.width * info.height) as usize;
+            let mut color = Vec::with_capacity(pixel_count * pixel_channels);
+            let mut alpha = Vec::with_capacity(pixel_count);
+            for pixel in buf.chunks_exact(channels) {
+                color.extend_from_slice(&pixel[..pixel_channels]);
+                alpha.push(pixel[pixel_channels]);
+            }
+            (color, Some(alpha))
+        } else {
+            (buf, None)
+        };
+
+        Ok(EmbeddedImage {
+            width: info.width,
+            height: info.height,
+            color_space,
+            samples: ImageSamples::Raw { color, alpha },
+        })
+    }
+
+    /// Builds the `/Image` XObject (and its `/SMask` sub-XObject, if the source had
+    /// an alpha channel) and adds them to `doc`, returning the id of the `/Image`
+    /// XObject to reference from `/Resources /XObject`.
+    pub fn build_xobject(&self, doc: &mut Document) -> ObjectId {
+        match &self.samples {
+            ImageSamples::Jpeg(bytes) => doc.add_object(Stream::new(
+                dictionary! {
+                    "Type" => "XObject",
+                    "Subtype" => "Image",
+                    "Width" => self.width as i64,
+                    "Height" => self.height as i64,
+                    "ColorSpace" => self.color_space,
+                    "BitsPerComponent" => 8,
+                    "Filter" => "DCTDecode",
+                },
+                bytes.clone(),
+            )),
+            ImageSamples::Raw { color, alpha } => {
+                let smask_id = alpha.as_ref().map(|alpha| {
+                    doc.add_object(Stream::new(
+                        dictionary! {
+                            "Type" => "XObject",
+                            "Subtype" => "Image",
+                            "Width" => self.width as i64,
+                            "Height" => self.height as i64,
+                            "ColorSpace" => "DeviceGray",
+                            "BitsPerComponent" => 8,
+                        },
+                        alpha.clone(),
+                    ))
+                });
+
+                let mut dict = dictionary! {
+                    "Type" => "XObject",
+                    "Subtype" => "Image",
+                    "Width" => self.width as i64,
+                    "Height" => self.height as i64,
+                    "ColorSpace" => self.color_space,
+                    "BitsPerComponent" => 8,
+                };
+                if let Some(smask_id) = smask_id {
+                    dict.set("SMask", smask_id);
+                }
+
+                doc.add_object(Stream::new(dict, color.clone()))
+            }
+        }
+    }
+}
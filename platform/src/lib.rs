@@ -1,135 +1,37 @@
+mod conformance;
+mod document;
+mod font;
+mod image;
+mod metadata;
+mod outline;
+mod subset;
+
+use conformance::Conformance;
 use core::ffi::c_void;
-use lopdf::content::{Content, Operation};
-use lopdf::dictionary;
-use lopdf::{Document, Object, ObjectId, Stream};
-use roc_std::{RocResult, RocStr};
-use std::cell::RefCell;
+use document::PdfDocument;
+use metadata::DocumentMetadata;
+use roc_std::{RocList, RocResult, RocStr};
+use std::collections::HashMap;
+use std::sync::OnceLock;
 use std::{alloc::Layout, mem::MaybeUninit, sync::Mutex};
 
-pub static DOCUMENT: Mutex<RefCell<Option<PdfDocument>>> = Mutex::new(RefCell::new(None));
+/// Index of a document handed out by `roc_fx_newDocument`. Returned to Roc as a
+/// plain `U64`.
+pub type DocId = u64;
 
-#[derive(Debug)]
-pub struct PdfDocument {
-    doc: Document,
+/// Every document a Roc program has open at once, keyed by `DocId`, so a single
+/// process can build many PDFs (e.g. a per-invoice batch) instead of just one.
+#[derive(Default)]
+struct Registry {
+    documents: HashMap<DocId, PdfDocument>,
+    next_id: DocId,
+}
 
-    #[allow(dead_code)]
-    pages_id: ObjectId,
-}
-
-impl PdfDocument {
-    fn new() -> Self {
-        let mut doc = Document::with_version("1.5");
-        let pages_id = doc.new_object_id();
-
-        let font_id = doc.add_object(dictionary! {
-            // type of dictionary
-            "Type" => "Font",
-            // type of font, type1 is simple postscript font
-            "Subtype" => "Type1",
-            // basefont is postscript name of font for type1 font.
-            // See PDF reference document for more details
-            "BaseFont" => "Courier",
-        });
-
-        // font dictionaries need to be added into resource dictionaries
-        // in order to be used.
-        // Resource dictionaries can contain more than just fonts,
-        // but normally just contains fonts
-        // Only one resource dictionary is allowed per page tree root
-        let resources_id = doc.add_object(dictionary! {
-            // fonts are actually triplely nested dictionaries. Fun!
-            "Font" => dictionary! {
-                // F1 is the font name used when writing text.
-                // It must be unique in the document. It does not
-                // have to be F1
-                "F1" => font_id,
-            },
-        });
-
-        // Content is a wrapper struct around an operations struct that contains a vector of operations
-        // The operations struct contains a vector of operations that match up with a particular PDF
-        // operator and operands.
-        // Reference the PDF reference for more details on these operators and operands.
-        // Note, the operators and operands are specified in a reverse order than they
-        // actually appear in the PDF file itself.
-        let content = Content {
-            operations: vec![
-                // BT begins a text element. it takes no operands
-                Operation::new("BT", vec![]),
-                // Tf specifies the font and font size. Font scaling is complicated in PDFs. Reference
-                // the reference for more info.
-                // The into() methods are defined based on their paired .from() methods (this
-                // functionality is built into rust), and are converting the provided values into
-                // An enum that represents the basic object types in PDF documents.
-                Operation::new("Tf", vec!["F1".into(), 48.into()]),
-                // Td adjusts the translation components of the text matrix. When used for the first
-                // time after BT, it sets the initial text position on the page.
-                // Note: PDF documents have Y=0 at the bottom. Thus 600 to print text near the top.
-                Operation::new("Td", vec![100.into(), 600.into()]),
-                // Tj prints a string literal to the page. By default, this is black text that is
-                // filled in. There are other operators that can produce various textual effects and
-                // colors
-                Operation::new("Tj", vec![Object::string_literal("Hello World!")]),
-                // ET ends the text element
-                Operation::new("ET", vec![]),
-            ],
-        };
-
-        // Streams are a dictionary followed by a sequence of bytes. What that sequence of bytes
-        // represents depends on context
-        // The stream dictionary is set internally to lopdf and normally doesn't
-        // need to be manually manipulated. It contains keys such as
-        // Length, Filter, DecodeParams, etc
-        //
-        // content is a stream of encoded content data.
-        let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
-
-        // Page is a dictionary that represents one page of a PDF file.
-        // It has a type, parent and contents
-        let page_id = doc.add_object(dictionary! {
-            "Type" => "Page",
-            "Parent" => pages_id,
-            "Contents" => content_id,
-        });
-
-        // Again, pages is the root of the page tree. The ID was already created
-        // at the top of the page, since we needed it to assign to the parent element of the page
-        // dictionary
-        //
-        // This is just the basic requirements for a page tree root object. There are also many
-        // additional entries that can be added to the dictionary if needed. Some of these can also be
-        // defined on the page dictionary itself, and not inherited from the page tree root.
-        let pages = dictionary! {
-            // Type of dictionary
-            "Type" => "Pages",
-            // Vector of page IDs in document. Normally would contain more than one ID and be produced
-            // using a loop of some kind
-            "Kids" => vec![page_id.into()],
-            // Page count
-            "Count" => 1,
-            // ID of resources dictionary, defined earlier
-            "Resources" => resources_id,
-            // a rectangle that defines the boundaries of the physical or digital media. This is the
-            // "Page Size"
-            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
-        };
-
-        // using insert() here, instead of add_object() since the id is already known.
-        doc.objects.insert(pages_id, Object::Dictionary(pages));
-
-        // Creating document catalog.
-        // There are many more entries allowed in the catalog dictionary.
-        let catalog_id = doc.add_object(dictionary! {
-            "Type" => "Catalog",
-            "Pages" => pages_id,
-        });
-
-        // Root key in trailer is set here to ID of document catalog,
-        // remainder of trailer is set during doc.save().
-        doc.trailer.set("Root", catalog_id);
-
-        PdfDocument { doc, pages_id }
-    }
+/// `HashMap::new` isn't `const`, so the registry is lazily built on first use
+/// instead of living in a `static` initializer directly.
+fn registry() -> &'static Mutex<Registry> {
+    static REGISTRY: OnceLock<Mutex<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(|| Mutex::new(Registry::default()))
 }
 
 /// # Safety
@@ -292,17 +194,6 @@ pub unsafe fn call_the_closure(closure_data_ptr: *const u8) -> i32 {
 
 #[no_mangle]
 pub extern "C" fn main() -> i32 {
-    {
-        let mut doc_guard = DOCUMENT.lock().unwrap();
-        match doc_guard.get_mut() {
-            Some(pdf) => {
-                dbg!(pdf);
-                panic!("expected document to be empty")
-            }
-            None => doc_guard.replace(Some(PdfDocument::new())),
-        };
-    }
-
     init();
 
     rust_main()
@@ -312,7 +203,25 @@ pub extern "C" fn main() -> i32 {
 // This is specifically a problem with static compilation and musl.
 // TODO: remove all of this when we switch to effect interpreter.
 pub fn init() {
-    let funcs: &[*const extern "C" fn()] = &[roc_fx_save as _];
+    let funcs: &[*const extern "C" fn()] = &[
+        roc_fx_newDocument as _,
+        roc_fx_saveDocument as _,
+        roc_fx_closeDocument as _,
+        roc_fx_addPage as _,
+        roc_fx_addText as _,
+        roc_fx_setFont as _,
+        roc_fx_loadFont as _,
+        roc_fx_setMetadata as _,
+        roc_fx_addBookmark as _,
+        roc_fx_setConformance as _,
+        roc_fx_addImage as _,
+        roc_fx_setStrokeColor as _,
+        roc_fx_setFillColor as _,
+        roc_fx_setLineWidth as _,
+        roc_fx_drawLine as _,
+        roc_fx_drawRect as _,
+        roc_fx_fillRect as _,
+    ];
     #[allow(forgetting_references)]
     std::mem::forget(std::hint::black_box(funcs));
     if cfg!(unix) {
@@ -323,18 +232,371 @@ pub fn init() {
     }
 }
 
+/// Creates a new, empty document and returns its `DocId`, to pass to every other
+/// authoring effect and to `roc_fx_saveDocument`. A process can hold many documents
+/// open at once -- e.g. to render a batch of invoices without saving and dropping
+/// each one before starting the next.
 #[no_mangle]
-pub extern "C" fn roc_fx_save(path: RocStr) -> RocResult<(), RocStr> {
-    let mut doc_guard = DOCUMENT.lock().unwrap();
+pub extern "C" fn roc_fx_newDocument() -> DocId {
+    let mut registry = registry().lock().unwrap();
+    let doc_id = registry.next_id;
+    registry.next_id += 1;
+    registry.documents.insert(doc_id, PdfDocument::new());
+    doc_id
+}
+
+/// Builds and writes the document identified by `doc_id` out to `path`, compressing
+/// its streams first. The document stays open afterwards, so it can still be
+/// inspected or (if the Roc program keeps adding to it) saved again.
+#[no_mangle]
+pub extern "C" fn roc_fx_saveDocument(doc_id: DocId, path: RocStr) -> RocResult<(), RocStr> {
+    let mut registry = registry().lock().unwrap();
 
-    match doc_guard.get_mut() {
-        None => RocResult::err(RocStr::from("DOCUMENT NOT FOUND")),
+    match registry.documents.get_mut(&doc_id) {
+        None => RocResult::err(RocStr::from(format!("no such document: {doc_id}").as_str())),
         Some(pdf) => {
-            pdf.doc.compress();
-            match pdf.doc.save(path.as_str()) {
+            if let Err(err) = pdf.build() {
+                return RocResult::err(RocStr::from(err.as_str()));
+            }
+
+            let doc = pdf.doc_mut();
+            doc.compress();
+
+            match doc.save(path.as_str()) {
                 Ok(..) => RocResult::ok(()),
                 Err(..) => RocResult::err(RocStr::from("ERROR SAVING DOCUMENT")),
             }
         }
     }
 }
+
+/// Drops `doc_id` from the registry, freeing its pages, fonts and images. Call this
+/// once a document has been saved (or abandoned) so a long-running batch -- e.g.
+/// rendering one PDF per invoice -- doesn't retain every document it has ever built.
+#[no_mangle]
+pub extern "C" fn roc_fx_closeDocument(doc_id: DocId) -> RocResult<(), RocStr> {
+    let mut registry = registry().lock().unwrap();
+
+    match registry.documents.remove(&doc_id) {
+        None => RocResult::err(RocStr::from(format!("no such document: {doc_id}").as_str())),
+        Some(_) => RocResult::ok(()),
+    }
+}
+
+/// Appends a new, initially blank page to `doc_id` and returns its `PageId`.
+///
+/// `width` and `height` are the page's `MediaBox` dimensions in PDF points
+/// (1/72 inch), e.g. 595.0 x 842.0 for A4.
+#[no_mangle]
+pub extern "C" fn roc_fx_addPage(
+    doc_id: DocId,
+    width: f64,
+    height: f64,
+) -> RocResult<u64, RocStr> {
+    let mut registry = registry().lock().unwrap();
+
+    match registry.documents.get_mut(&doc_id) {
+        None => RocResult::err(RocStr::from(format!("no such document: {doc_id}").as_str())),
+        Some(pdf) => RocResult::ok(pdf.add_page(width, height) as u64),
+    }
+}
+
+/// Registers one of the 14 standard Type1 fonts (e.g. `"Courier"`, `"Helvetica"`,
+/// `"Times-Roman"`) for use with `roc_fx_addText` and returns a `FontRef`.
+#[no_mangle]
+pub extern "C" fn roc_fx_setFont(doc_id: DocId, base_font: RocStr) -> RocResult<u64, RocStr> {
+    let mut registry = registry().lock().unwrap();
+
+    match registry.documents.get_mut(&doc_id) {
+        None => RocResult::err(RocStr::from(format!("no such document: {doc_id}").as_str())),
+        Some(pdf) => RocResult::ok(pdf.set_font(base_font.as_str().to_string()) as u64),
+    }
+}
+
+/// Parses and embeds a `.ttf`/`.otf` font so it can be used with `roc_fx_addText`,
+/// returning a `FontRef`. The font program is embedded as a composite
+/// (`/Type0`/`/CIDFontType2`) font, with its own `/W` widths array and `/ToUnicode`
+/// CMap, so multiple embedded fonts can coexist with the built-in ones.
+#[no_mangle]
+pub extern "C" fn roc_fx_loadFont(doc_id: DocId, bytes: RocList<u8>) -> RocResult<u64, RocStr> {
+    let mut registry = registry().lock().unwrap();
+
+    match registry.documents.get_mut(&doc_id) {
+        None => RocResult::err(RocStr::from(format!("no such document: {doc_id}").as_str())),
+        Some(pdf) => match pdf.load_font(bytes.as_slice().to_vec()) {
+            Ok(font_ref) => RocResult::ok(font_ref as u64),
+            Err(err) => RocResult::err(RocStr::from(err.as_str())),
+        },
+    }
+}
+
+/// Sets `doc_id`'s `/Info` metadata (title, author, subject, keywords, creator).
+/// Written both into the trailer `/Info` dictionary and an XMP `/Metadata` stream
+/// when `roc_fx_saveDocument` builds the document.
+#[no_mangle]
+pub extern "C" fn roc_fx_setMetadata(
+    doc_id: DocId,
+    title: RocStr,
+    author: RocStr,
+    subject: RocStr,
+    keywords: RocStr,
+    creator: RocStr,
+) -> RocResult<(), RocStr> {
+    let mut registry = registry().lock().unwrap();
+
+    match registry.documents.get_mut(&doc_id) {
+        None => RocResult::err(RocStr::from(format!("no such document: {doc_id}").as_str())),
+        Some(pdf) => {
+            pdf.set_metadata(DocumentMetadata {
+                title: title.as_str().to_string(),
+                author: author.as_str().to_string(),
+                subject: subject.as_str().to_string(),
+                keywords: keywords.as_str().to_string(),
+                creator: creator.as_str().to_string(),
+            });
+            RocResult::ok(())
+        }
+    }
+}
+
+/// Adds an outline (bookmark) entry pointing at the top of `page_id` in `doc_id`,
+/// returning a `BookmarkId`. Pass `u64::MAX` as `parent_bookmark` for a top-level
+/// entry, or an existing `BookmarkId` to nest it underneath that entry.
+#[no_mangle]
+pub extern "C" fn roc_fx_addBookmark(
+    doc_id: DocId,
+    title: RocStr,
+    page_id: u64,
+    parent_bookmark: u64,
+) -> RocResult<u64, RocStr> {
+    let mut registry = registry().lock().unwrap();
+
+    let parent = if parent_bookmark == u64::MAX {
+        None
+    } else {
+        Some(parent_bookmark as usize)
+    };
+
+    match registry.documents.get_mut(&doc_id) {
+        None => RocResult::err(RocStr::from(format!("no such document: {doc_id}").as_str())),
+        Some(pdf) => match pdf.add_bookmark(title.as_str().to_string(), page_id as usize, parent) {
+            Ok(bookmark_id) => RocResult::ok(bookmark_id as u64),
+            Err(err) => RocResult::err(RocStr::from(err.as_str())),
+        },
+    }
+}
+
+/// Sets `doc_id`'s PDF conformance level, e.g. `"PDF/A-2b"`. Conformant output gets a
+/// bumped PDF version, an embedded sRGB ICC `/OutputIntent`, PDF/A identification in
+/// the XMP packet, and a trailer `/ID`; `roc_fx_saveDocument` errors out instead of
+/// silently producing a non-conformant file if e.g. a non-embedded font is in use.
+#[no_mangle]
+pub extern "C" fn roc_fx_setConformance(doc_id: DocId, level: RocStr) -> RocResult<(), RocStr> {
+    let mut registry = registry().lock().unwrap();
+
+    let conformance = match Conformance::parse(level.as_str()) {
+        Ok(conformance) => conformance,
+        Err(err) => return RocResult::err(RocStr::from(err.as_str())),
+    };
+
+    match registry.documents.get_mut(&doc_id) {
+        None => RocResult::err(RocStr::from(format!("no such document: {doc_id}").as_str())),
+        Some(pdf) => {
+            pdf.set_conformance(conformance);
+            RocResult::ok(())
+        }
+    }
+}
+
+/// Draws a string of text on the given page, using the font and size given, with the
+/// text's baseline starting at `(x, y)` in PDF points measured from the bottom-left
+/// corner of the page.
+#[no_mangle]
+pub extern "C" fn roc_fx_addText(
+    doc_id: DocId,
+    page_id: u64,
+    font_ref: u64,
+    size: f64,
+    x: f64,
+    y: f64,
+    text: RocStr,
+) -> RocResult<(), RocStr> {
+    let mut registry = registry().lock().unwrap();
+
+    match registry.documents.get_mut(&doc_id) {
+        None => RocResult::err(RocStr::from(format!("no such document: {doc_id}").as_str())),
+        Some(pdf) => match pdf.add_text(
+            page_id as usize,
+            font_ref as usize,
+            size,
+            x,
+            y,
+            text.as_str(),
+        ) {
+            Ok(()) => RocResult::ok(()),
+            Err(err) => RocResult::err(RocStr::from(err.as_str())),
+        },
+    }
+}
+
+/// Sets the `RG` stroke color used by later `roc_fx_drawLine` / `roc_fx_drawRect`
+/// calls on `page_id`. `r`, `g`, `b` are 0-1 RGB components.
+#[no_mangle]
+pub extern "C" fn roc_fx_setStrokeColor(
+    doc_id: DocId,
+    page_id: u64,
+    r: f64,
+    g: f64,
+    b: f64,
+) -> RocResult<(), RocStr> {
+    let mut registry = registry().lock().unwrap();
+
+    match registry.documents.get_mut(&doc_id) {
+        None => RocResult::err(RocStr::from(format!("no such document: {doc_id}").as_str())),
+        Some(pdf) => match pdf.set_stroke_color(page_id as usize, r, g, b) {
+            Ok(()) => RocResult::ok(()),
+            Err(err) => RocResult::err(RocStr::from(err.as_str())),
+        },
+    }
+}
+
+/// Sets the `rg` fill color used by later `roc_fx_fillRect` calls on `page_id`.
+/// `r`, `g`, `b` are 0-1 RGB components.
+#[no_mangle]
+pub extern "C" fn roc_fx_setFillColor(
+    doc_id: DocId,
+    page_id: u64,
+    r: f64,
+    g: f64,
+    b: f64,
+) -> RocResult<(), RocStr> {
+    let mut registry = registry().lock().unwrap();
+
+    match registry.documents.get_mut(&doc_id) {
+        None => RocResult::err(RocStr::from(format!("no such document: {doc_id}").as_str())),
+        Some(pdf) => match pdf.set_fill_color(page_id as usize, r, g, b) {
+            Ok(()) => RocResult::ok(()),
+            Err(err) => RocResult::err(RocStr::from(err.as_str())),
+        },
+    }
+}
+
+/// Sets the `w` line width, in PDF points, used by later `roc_fx_drawLine` /
+/// `roc_fx_drawRect` calls on `page_id`.
+#[no_mangle]
+pub extern "C" fn roc_fx_setLineWidth(
+    doc_id: DocId,
+    page_id: u64,
+    width: f64,
+) -> RocResult<(), RocStr> {
+    let mut registry = registry().lock().unwrap();
+
+    match registry.documents.get_mut(&doc_id) {
+        None => RocResult::err(RocStr::from(format!("no such document: {doc_id}").as_str())),
+        Some(pdf) => match pdf.set_line_width(page_id as usize, width) {
+            Ok(()) => RocResult::ok(()),
+            Err(err) => RocResult::err(RocStr::from(err.as_str())),
+        },
+    }
+}
+
+/// Strokes a straight line from `(x1, y1)` to `(x2, y2)` on `page_id`, using its
+/// current stroke color and line width.
+#[no_mangle]
+pub extern "C" fn roc_fx_drawLine(
+    doc_id: DocId,
+    page_id: u64,
+    x1: f64,
+    y1: f64,
+    x2: f64,
+    y2: f64,
+) -> RocResult<(), RocStr> {
+    let mut registry = registry().lock().unwrap();
+
+    match registry.documents.get_mut(&doc_id) {
+        None => RocResult::err(RocStr::from(format!("no such document: {doc_id}").as_str())),
+        Some(pdf) => match pdf.draw_line(page_id as usize, x1, y1, x2, y2) {
+            Ok(()) => RocResult::ok(()),
+            Err(err) => RocResult::err(RocStr::from(err.as_str())),
+        },
+    }
+}
+
+/// Strokes the outline of a `width` x `height` rectangle with its lower-left corner
+/// at `(x, y)` on `page_id`, using its current stroke color and line width.
+#[no_mangle]
+pub extern "C" fn roc_fx_drawRect(
+    doc_id: DocId,
+    page_id: u64,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+) -> RocResult<(), RocStr> {
+    let mut registry = registry().lock().unwrap();
+
+    match registry.documents.get_mut(&doc_id) {
+        None => RocResult::err(RocStr::from(format!("no such document: {doc_id}").as_str())),
+        Some(pdf) => match pdf.draw_rect(page_id as usize, x, y, width, height) {
+            Ok(()) => RocResult::ok(()),
+            Err(err) => RocResult::err(RocStr::from(err.as_str())),
+        },
+    }
+}
+
+/// Fills a `width` x `height` rectangle with its lower-left corner at `(x, y)` on
+/// `page_id`, using its current fill color.
+#[no_mangle]
+pub extern "C" fn roc_fx_fillRect(
+    doc_id: DocId,
+    page_id: u64,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+) -> RocResult<(), RocStr> {
+    let mut registry = registry().lock().unwrap();
+
+    match registry.documents.get_mut(&doc_id) {
+        None => RocResult::err(RocStr::from(format!("no such document: {doc_id}").as_str())),
+        Some(pdf) => match pdf.fill_rect(page_id as usize, x, y, width, height) {
+            Ok(()) => RocResult::ok(()),
+            Err(err) => RocResult::err(RocStr::from(err.as_str())),
+        },
+    }
+}
+
+/// Decodes `bytes` as a `"jpeg"` or `"png"` image and draws it on the given page as
+/// an `/Image` XObject, scaled to `width` x `height` PDF points with its lower-left
+/// corner at `(x, y)`. JPEG bytes are embedded verbatim under `/DCTDecode`; PNG is
+/// fully decoded to raw samples (and a `/SMask` XObject, if it has an alpha channel)
+/// for `roc_fx_saveDocument` to compress with `/FlateDecode`.
+#[no_mangle]
+pub extern "C" fn roc_fx_addImage(
+    doc_id: DocId,
+    page_id: u64,
+    bytes: RocList<u8>,
+    format: RocStr,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+) -> RocResult<(), RocStr> {
+    let mut registry = registry().lock().unwrap();
+
+    match registry.documents.get_mut(&doc_id) {
+        None => RocResult::err(RocStr::from(format!("no such document: {doc_id}").as_str())),
+        Some(pdf) => match pdf.add_image(
+            page_id as usize,
+            bytes.as_slice().to_vec(),
+            format.as_str(),
+            x,
+            y,
+            width,
+            height,
+        ) {
+            Ok(()) => RocResult::ok(()),
+            Err(err) => RocResult::err(RocStr::from(err.as_str())),
+        },
+    }
+}
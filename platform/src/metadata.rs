@@ -0,0 +1,136 @@
+use lopdf::{dictionary, Document, Object, Stream};
+
+/// Document metadata set with `roc_fx_setMetadata`. Mirrors printpdf's `PdfMetadata`:
+/// written both into the trailer `/Info` dictionary and, at save time, into an XMP
+/// `/Metadata` stream referenced from the catalog.
+#[derive(Debug, Default, Clone)]
+pub struct DocumentMetadata {
+    pub title: String,
+    pub author: String,
+    pub subject: String,
+    pub keywords: String,
+    pub creator: String,
+}
+
+/// Formats `timestamp` (seconds since the Unix epoch) as a PDF date string,
+/// `D:YYYYMMDDHHmmSS`, as used by `/CreationDate` and `/ModDate`.
+pub fn pdf_date(timestamp: i64) -> String {
+    let days_since_epoch = timestamp.div_euclid(86_400);
+    let seconds_of_day = timestamp.rem_euclid(86_400);
+
+    let (year, month, day) = civil_from_days(days_since_epoch);
+    let hour = seconds_of_day / 3600;
+    let minute = (seconds_of_day % 3600) / 60;
+    let second = seconds_of_day % 60;
+
+    format!("D:{year:04}{month:02}{day:02}{hour:02}{minute:02}{second:02}")
+}
+
+/// Civil calendar date from a day count relative to the Unix epoch (1970-01-01),
+/// using Howard Hinnant's well known `civil_from_days` algorithm.
+fn civil_from_days(z: i64) -> (i64, u32, u32) {
+    let z = z + 719_468;
+    let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+    let doe = (z - era * 146_097) as u64;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let y = yoe as i64 + era * 400;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = if m <= 2 { y + 1 } else { y };
+
+    (y, m, d)
+}
+
+impl DocumentMetadata {
+    /// Writes `/Title`, `/Author`, `/Subject`, `/Keywords`, `/Creator`, `/CreationDate`
+    /// and `/ModDate` into the trailer's `/Info` dictionary.
+    pub fn write_info_dict(&self, doc: &mut Document, now: i64) {
+        let date = pdf_date(now);
+
+        let info_id = doc.add_object(dictionary! {
+            "Title" => Object::string_literal(self.title.as_str()),
+            "Author" => Object::string_literal(self.author.as_str()),
+            "Subject" => Object::string_literal(self.subject.as_str()),
+            "Keywords" => Object::string_literal(self.keywords.as_str()),
+            "Creator" => Object::string_literal(self.creator.as_str()),
+            "CreationDate" => Object::string_literal(date.as_str()),
+            "ModDate" => Object::string_literal(date.as_str()),
+        });
+
+        doc.trailer.set("Info", info_id);
+    }
+
+    /// Builds the XMP metadata packet referenced from the catalog's `/Metadata` entry.
+    /// When `pdfaid` is set (part, conformance), e.g. `("2", "B")` for PDF/A-2b, the
+    /// packet also declares the `pdfaid:part`/`pdfaid:conformance` PDF/A identification.
+    pub fn build_xmp_stream(&self, now: i64, pdfaid: Option<(&str, &str)>) -> Stream {
+        let date = xmp_date(now);
+
+        let pdfaid_elements = pdfaid
+            .map(|(part, conformance)| {
+                format!(
+                    "\n\x20     <pdfaid:part>{part}</pdfaid:part>\n\
+                     \x20     <pdfaid:conformance>{conformance}</pdfaid:conformance>"
+                )
+            })
+            .unwrap_or_default();
+
+        let xmp = format!(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+             <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+             \x20 <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+             \x20   <rdf:Description rdf:about=\"\"\n\
+             \x20       xmlns:dc=\"http://purl.org/dc/elements/1.1/\"\n\
+             \x20       xmlns:pdf=\"http://ns.adobe.com/pdf/1.3/\"\n\
+             \x20       xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"\n\
+             \x20       xmlns:pdfaid=\"http://www.aiim.org/pdfa/ns/id/\">\n\
+             \x20     <dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{title}</rdf:li></rdf:Alt></dc:title>\n\
+             \x20     <dc:creator><rdf:Seq><rdf:li>{author}</rdf:li></rdf:Seq></dc:creator>\n\
+             \x20     <dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{subject}</rdf:li></rdf:Alt></dc:description>\n\
+             \x20     <pdf:Keywords>{keywords}</pdf:Keywords>\n\
+             \x20     <xmp:CreatorTool>{creator}</xmp:CreatorTool>\n\
+             \x20     <xmp:CreateDate>{date}</xmp:CreateDate>\n\
+             \x20     <xmp:ModifyDate>{date}</xmp:ModifyDate>{pdfaid_elements}\n\
+             \x20   </rdf:Description>\n\
+             \x20 </rdf:RDF>\n\
+             </x:xmpmeta>\n\
+             <?xpacket end=\"w\"?>",
+            title = xml_escape(&self.title),
+            author = xml_escape(&self.author),
+            subject = xml_escape(&self.subject),
+            keywords = xml_escape(&self.keywords),
+            creator = xml_escape(&self.creator),
+            date = date,
+            pdfaid_elements = pdfaid_elements,
+        );
+
+        Stream::new(
+            dictionary! {
+                "Type" => "Metadata",
+                "Subtype" => "XML",
+            },
+            xmp.into_bytes(),
+        )
+    }
+}
+
+/// Formats `timestamp` as an ISO-8601 date, the form XMP's `xmp:CreateDate` expects.
+fn xmp_date(timestamp: i64) -> String {
+    let days_since_epoch = timestamp.div_euclid(86_400);
+    let seconds_of_day = timestamp.rem_euclid(86_400);
+
+    let (year, month, day) = civil_from_days(days_since_epoch);
+    let hour = seconds_of_day / 3600;
+    let minute = (seconds_of_day % 3600) / 60;
+    let second = seconds_of_day % 60;
+
+    format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}Z")
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
@@ -0,0 +1,215 @@
+use lopdf::{dictionary, Document, Object, ObjectId};
+
+/// One entry accumulated by `roc_fx_addBookmark`, building up the document outline
+/// (bookmark sidebar) the way printpdf's `bookmarks: HashMap<usize, String>` does,
+/// except we also need a tree shape, so parent/child links are tracked here rather
+/// than flattened.
+#[derive(Debug)]
+pub struct BookmarkEntry {
+    pub title: String,
+    pub page_id: usize,
+    pub parent: Option<usize>,
+}
+
+/// Index of a bookmark accumulated on a [`PdfDocument`](crate::document::PdfDocument).
+pub type BookmarkId = usize;
+
+/// Builds the `/Outlines` object tree from a flat list of [`BookmarkEntry`] and
+/// returns the id of the root `/Outlines` dictionary, or `None` if there are no
+/// bookmarks. `pages[i]` must be the object id and `MediaBox` height of the page at
+/// index `i`, so each bookmark's `/Dest` can target the top of its page rather than
+/// the bottom.
+pub fn build_outline(
+    doc: &mut Document,
+    bookmarks: &[BookmarkEntry],
+    pages: &[(ObjectId, f64)],
+) -> Option<ObjectId> {
+    if bookmarks.is_empty() {
+        return None;
+    }
+
+    let outline_root_id = doc.new_object_id();
+
+    // Children of each bookmark (including the implicit root, represented as `None`),
+    // in the order `roc_fx_addBookmark` was called.
+    let mut children: Vec<Vec<BookmarkId>> = vec![Vec::new(); bookmarks.len() + 1];
+    for (id, bookmark) in bookmarks.iter().enumerate() {
+        let parent_slot = bookmark.parent.map(|p| p + 1).unwrap_or(0);
+        children[parent_slot].push(id);
+    }
+
+    // One object id per bookmark, allocated up front so siblings can reference each
+    // other's `/Prev` and `/Next` regardless of build order.
+    let node_ids: Vec<ObjectId> = bookmarks.iter().map(|_| doc.new_object_id()).collect();
+
+    for (id, bookmark) in bookmarks.iter().enumerate() {
+        let parent_slot = bookmark.parent.map(|p| p + 1).unwrap_or(0);
+        let siblings = &children[parent_slot];
+        let position = siblings.iter().position(|&sibling| sibling == id).unwrap();
+
+        let parent_id = bookmark
+            .parent
+            .map(|p| node_ids[p])
+            .unwrap_or(outline_root_id);
+
+        let kids = &children[id + 1];
+        let descendant_count = count_descendants(&children, id + 1);
+
+        let mut dict = dictionary! {
+            "Title" => Object::string_literal(bookmark.title.as_str()),
+            "Parent" => parent_id,
+        };
+
+        if position > 0 {
+            dict.set("Prev", node_ids[siblings[position - 1]]);
+        }
+        if position + 1 < siblings.len() {
+            dict.set("Next", node_ids[siblings[position + 1]]);
+        }
+        if let (Some(&first), Some(&last)) = (kids.first(), kids.last()) {
+            dict.set("First", node_ids[first]);
+            dict.set("Last", node_ids[last]);
+            // Negative Count: this node's children start out collapsed.
+            dict.set("Count", -(descendant_count as i64));
+        }
+
+        if let Some(&(page_ref, page_height)) = pages.get(bookmark.page_id) {
+            dict.set(
+                "Dest",
+                vec![
+                    page_ref.into(),
+                    Object::Name(b"XYZ".to_vec()),
+                    0.into(),
+                    page_height.into(),
+                    0.into(),
+                ],
+            );
+        }
+
+        doc.objects.insert(node_ids[id], Object::Dictionary(dict));
+    }
+
+    let root_kids = &children[0];
+    let mut root_dict = dictionary! {
+        "Type" => "Outlines",
+    };
+    if let (Some(&first), Some(&last)) = (root_kids.first(), root_kids.last()) {
+        root_dict.set("First", node_ids[first]);
+        root_dict.set("Last", node_ids[last]);
+        root_dict.set("Count", root_kids.len() as i64);
+    }
+    doc.objects
+        .insert(outline_root_id, Object::Dictionary(root_dict));
+
+    Some(outline_root_id)
+}
+
+fn count_descendants(children: &[Vec<BookmarkId>], slot: usize) -> usize {
+    children[slot]
+        .iter()
+        .map(|&id| 1 + count_descendants(children, id + 1))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(doc: &Document, id: ObjectId) -> &lopdf::Dictionary {
+        doc.objects.get(&id).unwrap().as_dict().unwrap()
+    }
+
+    fn reference(dict: &lopdf::Dictionary, key: &[u8]) -> Option<ObjectId> {
+        dict.get(key).ok().and_then(|obj| obj.as_reference().ok())
+    }
+
+    #[test]
+    fn build_outline_returns_none_without_bookmarks() {
+        let mut doc = Document::new();
+        assert!(build_outline(&mut doc, &[], &[]).is_none());
+    }
+
+    #[test]
+    fn build_outline_links_siblings_and_collapses_new_nodes() {
+        let mut doc = Document::new();
+        let page_a: ObjectId = (10, 0);
+        let page_b: ObjectId = (11, 0);
+        let pages = vec![(page_a, 792.0), (page_b, 500.0)];
+
+        // A tree of:
+        //   A
+        //   |-- A.1
+        //   `-- A.2
+        //   B
+        let bookmarks = vec![
+            BookmarkEntry {
+                title: "A".into(),
+                page_id: 0,
+                parent: None,
+            },
+            BookmarkEntry {
+                title: "A.1".into(),
+                page_id: 0,
+                parent: Some(0),
+            },
+            BookmarkEntry {
+                title: "A.2".into(),
+                page_id: 0,
+                parent: Some(0),
+            },
+            BookmarkEntry {
+                title: "B".into(),
+                page_id: 1,
+                parent: None,
+            },
+        ];
+
+        let root_id =
+            build_outline(&mut doc, &bookmarks, &pages).expect("non-empty bookmarks produce an outline");
+        let root = node(&doc, root_id);
+
+        // Two top-level bookmarks: "A" and "B".
+        assert_eq!(root.get(b"Count").unwrap().as_i64().unwrap(), 2);
+        let a_id = reference(root, b"First").unwrap();
+        let b_id = reference(root, b"Last").unwrap();
+        assert_ne!(a_id, b_id);
+
+        let a = node(&doc, a_id);
+        assert_eq!(reference(a, b"Parent").unwrap(), root_id);
+        assert!(reference(a, b"Prev").is_none());
+        assert_eq!(reference(a, b"Next").unwrap(), b_id);
+        // "A" starts collapsed: negative Count of its two children.
+        assert_eq!(a.get(b"Count").unwrap().as_i64().unwrap(), -2);
+
+        let a1_id = reference(a, b"First").unwrap();
+        let a2_id = reference(a, b"Last").unwrap();
+        assert_ne!(a1_id, a2_id);
+
+        let a1 = node(&doc, a1_id);
+        assert_eq!(reference(a1, b"Parent").unwrap(), a_id);
+        assert!(reference(a1, b"Prev").is_none());
+        assert_eq!(reference(a1, b"Next").unwrap(), a2_id);
+        assert!(a1.get(b"First").is_err(), "leaf bookmarks have no children to collapse");
+
+        let a2 = node(&doc, a2_id);
+        assert_eq!(reference(a2, b"Parent").unwrap(), a_id);
+        assert_eq!(reference(a2, b"Prev").unwrap(), a1_id);
+        assert!(reference(a2, b"Next").is_none());
+
+        let b = node(&doc, b_id);
+        assert_eq!(reference(b, b"Parent").unwrap(), root_id);
+        assert_eq!(reference(b, b"Prev").unwrap(), a_id);
+        assert!(reference(b, b"Next").is_none());
+        assert!(b.get(b"Count").is_err());
+
+        // `/Dest` targets the top of each bookmark's own page, not the bottom, and
+        // not another bookmark's page.
+        let a_dest = a.get(b"Dest").unwrap().as_array().unwrap();
+        assert_eq!(a_dest[0].as_reference().unwrap(), page_a);
+        assert_eq!(a_dest[3].as_f64().unwrap(), 792.0);
+
+        let b_dest = b.get(b"Dest").unwrap().as_array().unwrap();
+        assert_eq!(b_dest[0].as_reference().unwrap(), page_b);
+        assert_eq!(b_dest[3].as_f64().unwrap(), 500.0);
+    }
+}